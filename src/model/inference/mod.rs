@@ -1,22 +1,56 @@
 //! The inferencing part, leveraging the `ort` ONNX wrapper
 
+use std::collections::HashMap;
 use std::path::Path;
+use ndarray::{ArrayD, Axis, IxDyn};
 use ort::session::{SessionInputs, SessionOutputs};
 use ort::session::{builder::GraphOptimizationLevel, Session};
 use crate::util::compose::Composable;
 use crate::util::result::Result;
-use super::params::{Parameters, RuntimeParameters};
+use super::input::encoded::EncodedInput;
+use super::output::{consolidate_with_params, decode_spans, Span};
+use super::params::{Parameters, Precision, RuntimeParameters};
 use super::pipeline::Pipeline;
 
+/// Named output tensors from one or more `session.run` calls, merged back into the
+/// caller's original row order (see [`Model::run_encoded`]). Each tensor keeps its
+/// original rank; only axis 0 (the batch/row axis) is stitched across buckets.
+#[derive(Default)]
+pub struct MergedOutputs {
+    pub tensors: HashMap<String, ArrayD<f32>>,
+}
+
 
 /// A `Model` can load an ONNX model, and run it using the provided pipeline.
-pub struct Model {    
+pub struct Model {
     session: Session,
+    /// See `RuntimeParameters::bucket_rows`.
+    bucket_rows: usize,
+    /// See `RuntimeParameters::max_batch_tokens`.
+    max_batch_tokens: Option<usize>,
+    /// See `RuntimeParameters::precision`. Determines the dtype `run_encoded` extracts
+    /// the session's output tensors as (fp16 weights still produce fp16 logits).
+    precision: Precision,
 }
 
 
-impl Model {    
+impl Model {
     pub fn new<P: AsRef<Path>>(model_path: P, params: RuntimeParameters) -> Result<Self> {
+        // fp16 kernels in `ort` are typically GPU-only: committing at fp16 without an
+        // actual GPU-capable execution provider configured (e.g. CUDA) would either
+        // fail deep inside the session or silently fall back to a slow, unintended CPU
+        // path. Checking the list isn't empty isn't enough -- a CPU-only list must still
+        // be rejected, so require at least one provider whose own identifier is
+        // something other than "CPU" (a CPU provider listed alongside it is fine).
+        if params.precision == Precision::Fp16 {
+            let has_gpu_provider = params.execution_providers.iter()
+                .any(|provider| !format!("{provider:?}").contains("CPU"));
+            if !has_gpu_provider {
+                return Err("Precision::Fp16 requires a GPU-capable execution provider (e.g. CUDA) in RuntimeParameters::execution_providers; fp16 kernels are not generally available on CPU".into());
+            }
+        }
+
+        let model_path = params.precision.resolve_model_path(model_path.as_ref());
         let session = Session::builder()?
             .with_execution_providers(params.execution_providers)?
             .with_optimization_level(GraphOptimizationLevel::Level3)?
@@ -25,17 +59,34 @@ impl Model {
 
         Ok(Self {
             session,
+            bucket_rows: params.bucket_rows,
+            max_batch_tokens: params.max_batch_tokens,
+            precision: params.precision,
         })
-    }    
+    }
 
-    
+
+    /// NOTE for whoever next touches `pipeline.rs` (not present in this checkout):
+    /// this changed what `Pipeline::post_processor`'s `Composable` must accept as its
+    /// input half -- it used to be `(SessionOutputs<'_, '_>, Meta)`, it is now
+    /// `(Vec<Vec<crate::model::output::Span>>, Meta)`, i.e. `infer_spans`'s already
+    /// decoded-and-consolidated per-row spans rather than the raw model output. Every
+    /// concrete `Pipeline` implementor's `post_processor` needs its input type (and
+    /// body) updated to match before this will compile against the full tree.
     pub fn inference<'a, P: Pipeline<'a>>(&'a self, input: P::Input, pipeline: &P, params: &Parameters) -> Result<P::Output> {
-        // pre-process
-        let (input, meta) = pipeline.pre_processor(params).apply(input)?;
-        // inference
-        let output = self.run(input)?;                
+        // pre-process -- every pipeline's pre-processor bottoms out at `EncodedInput`
+        // (see `PromptsToEncoded`'s `Composable<PromptInput, EncodedInput>`), so
+        // `Model::inference` itself owns turning that into model output from here,
+        // rather than each pipeline building `SessionInputs`/decoding spans by hand.
+        let (encoded, meta) = pipeline.pre_processor(params).apply(input)?;
+        // inference + consolidation: `infer_spans` drives both `run_encoded`'s
+        // `bucket_rows`/`max_batch_tokens` length-bucketing (see
+        // `EncodedInput::into_buckets`) and `consolidate_with_params`'s
+        // `consolidation_strategy`/`threshold`, so both are real, load-bearing parts
+        // of every inference call instead of configuration only reachable by hand.
+        let spans = self.infer_spans(encoded, params)?;
         // post-process
-        let output = pipeline.post_processor(params).apply((output, meta))?;        
+        let output = pipeline.post_processor(params).apply((spans, meta))?;
         // ok
         Ok(output)
     }
@@ -45,5 +96,172 @@ impl Model {
         Ok(self.session.run(input)?)
     }
 
+    /// Runs `encoded` through the session, transparently splitting it into
+    /// length-bucketed mini-batches (`self.bucket_rows`/`self.max_batch_tokens`, see
+    /// `EncodedInput::into_buckets`) so a batch with one long row and many short ones
+    /// never pads every row to the longest. Every bucket's output tensors are merged
+    /// back into `encoded`'s original row order, so callers never see bucket
+    /// boundaries.
+    pub fn run_encoded(&self, encoded: EncodedInput) -> Result<MergedOutputs> {
+        let total_rows = encoded.input_ids.nrows();
+        let buckets = encoded.into_buckets(self.bucket_rows, self.max_batch_tokens)?;
+
+        let mut merged = MergedOutputs::default();
+        for (indices, bucket) in buckets {
+            let session_inputs = ort::inputs![
+                "input_ids" => bucket.input_ids.clone(),
+                "attention_masks" => bucket.attention_masks.clone(),
+                "word_masks" => bucket.word_masks.clone(),
+                "text_lengths" => bucket.text_lengths.clone(),
+            ]?;
+            let outputs = self.run(session_inputs.into())?;
+            for (name, value) in outputs.iter() {
+                // A `Fp32`/`Int8` session's outputs already come back as f32 logits, but
+                // a `Fp16` session's weights produce fp16 logits -- widen those here so
+                // every caller downstream of `run_encoded` (consolidation, span
+                // decoding) can keep working in f32 without needing to know the
+                // session's precision.
+                let tensor: ArrayD<f32> = match self.precision {
+                    Precision::Fp16 => value.try_extract_tensor::<half::f16>()?.mapv(|logit| logit.to_f32()),
+                    Precision::Fp32 | Precision::Int8 => value.try_extract_tensor::<f32>()?.to_owned(),
+                };
+                merged.merge_bucket(name, &tensor, &indices, total_rows);
+            }
+        }
+
+        Ok(merged)
+    }
+
+    /// Runs `encoded` (via [`Self::run_encoded`]) and decodes+consolidates its
+    /// `"logits"` output tensor into a clean, non-contradictory entity set per row,
+    /// driven entirely by `params.consolidation_strategy`/`params.threshold` (see
+    /// [`crate::model::output::consolidate_with_params`]). This is the real consumer
+    /// of those `Parameters` fields during inference, rather than leaving
+    /// `consolidate` callable only from tests.
+    ///
+    /// Assumes the graph's `"logits"` output has shape `[rows, num_words, num_words,
+    /// num_entities]` (see [`crate::model::output::decode_spans`]).
+    pub fn infer_spans(&self, encoded: EncodedInput, params: &Parameters) -> Result<Vec<Vec<Span>>> {
+        let entities = encoded.entities.clone();
+        let text_lengths: Vec<usize> = encoded.text_lengths.outer_iter().map(|row| row[0] as usize).collect();
+
+        let merged = self.run_encoded(encoded)?;
+        let logits = merged.tensors.get("logits").ok_or_else(|| "model produced no \"logits\" output tensor".into())?;
+
+        text_lengths.iter().enumerate()
+            .map(|(row, &num_words)| {
+                let row_tensor = logits.index_axis(Axis(0), row).into_dimensionality::<ndarray::Ix3>()
+                    .map_err(|error| format!("\"logits\" output tensor has unexpected shape: {error}").into())?;
+                let candidates = decode_spans(row_tensor, num_words, &entities);
+                Ok(consolidate_with_params(candidates, params))
+            })
+            .collect()
+    }
+
+}
+
+impl MergedOutputs {
+    /// Scatters one bucket's rows of a named output tensor into their original
+    /// positions, allocating the full-size (`total_rows`) tensor for `name` on first
+    /// sight. Buckets are padded independently to their own length (see
+    /// `EncodedInput::into_buckets`), so the non-batch axes (e.g. `logits`'s per-row
+    /// `num_words` dims) legitimately differ between buckets -- `full` is grown to fit
+    /// the largest one seen so far before any row is scattered into it, and each row is
+    /// assigned into the leading sub-region matching its own bucket's (possibly
+    /// smaller) shape, leaving the rest zero-padded.
+    fn merge_bucket(&mut self, name: &str, tensor: &ArrayD<f32>, indices: &[usize], total_rows: usize) {
+        let full = self.tensors.entry(name.to_string()).or_insert_with(|| {
+            let mut shape = tensor.shape().to_vec();
+            shape[0] = total_rows;
+            ArrayD::zeros(IxDyn(&shape))
+        });
 
+        let needs_growing = tensor.shape()[1..].iter().zip(full.shape()[1..].iter())
+            .any(|(&bucket_dim, &full_dim)| bucket_dim > full_dim);
+        if needs_growing {
+            let mut grown_shape = full.shape().to_vec();
+            for (dim, &bucket_dim) in grown_shape.iter_mut().zip(tensor.shape().iter()).skip(1) {
+                *dim = (*dim).max(bucket_dim);
+            }
+            let mut grown = ArrayD::zeros(IxDyn(&grown_shape));
+            let old_shape = full.shape().to_vec();
+            grown.slice_each_axis_mut(|axis| ndarray::Slice::from(0..old_shape[axis.axis.index()] as isize))
+                .assign(full);
+            *full = grown;
+        }
+
+        for (bucket_row, &original_row) in indices.iter().enumerate() {
+            let bucket_row_view = tensor.index_axis(Axis(0), bucket_row);
+            let bucket_row_shape = bucket_row_view.shape().to_vec();
+            full.index_axis_mut(Axis(0), original_row)
+                .slice_each_axis_mut(|axis| ndarray::Slice::from(0..bucket_row_shape[axis.axis.index()] as isize))
+                .assign(&bucket_row_view);
+        }
+    }
+}
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_bucket_grows_to_fit_a_later_larger_bucket() {
+        use ndarray::{array, Array3};
+
+        let mut merged = MergedOutputs::default();
+        // First bucket: 2 rows, shape [2, 1, 1] -- narrower than the second bucket.
+        let small: ArrayD<f32> = Array3::from_shape_vec((2, 1, 1), vec![1.0, 2.0]).unwrap().into_dyn();
+        merged.merge_bucket("logits", &small, &[0, 2], 3);
+        // Second bucket: 1 row, shape [1, 3, 1] -- wider than the first.
+        let large: ArrayD<f32> = Array3::from_shape_vec((1, 3, 1), vec![10.0, 20.0, 30.0]).unwrap().into_dyn();
+        merged.merge_bucket("logits", &large, &[1], 3);
+
+        let full = &merged.tensors["logits"];
+        assert_eq!(full.shape(), &[3, 3, 1]);
+        // Row 0 (from the small bucket) keeps its value in the leading slot, zero-padded after.
+        assert_eq!(full.index_axis(Axis(0), 0), array![[1.0], [0.0], [0.0]].into_dyn());
+        // Row 1 (from the large bucket) is fully populated.
+        assert_eq!(full.index_axis(Axis(0), 1), array![[10.0], [20.0], [30.0]].into_dyn());
+        // Row 2 (from the small bucket) also keeps its leading value only.
+        assert_eq!(full.index_axis(Axis(0), 2), array![[2.0], [0.0], [0.0]].into_dyn());
+    }
+
+    #[test]
+    fn test_fp16_without_execution_provider_errors() {
+        // The precision check runs before the model file is ever touched, so this
+        // doesn't need a real `.onnx` file.
+        let params = RuntimeParameters { precision: Precision::Fp16, ..RuntimeParameters::default() };
+        let result = Model::new("nonexistent.onnx", params);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_fp16_with_only_a_cpu_execution_provider_still_errors() {
+        // A CPU-only provider list must still be rejected -- fp16 kernels aren't
+        // generally available on CPU, so listing *some* provider isn't enough.
+        let params = RuntimeParameters {
+            precision: Precision::Fp16,
+            execution_providers: vec![ort::execution_providers::CPUExecutionProvider::default().build()],
+            ..RuntimeParameters::default()
+        };
+        let result = Model::new("nonexistent.onnx", params);
+        let message = result.err().map(|error| error.to_string()).unwrap_or_default();
+        assert!(message.contains("requires a GPU-capable execution provider"));
+    }
+
+    #[test]
+    fn test_fp16_with_gpu_execution_provider_passes_the_precision_check() {
+        // Can't assert success without a real `.onnx` file + execution provider, but we
+        // can assert the failure (from the missing file) is no longer the
+        // `Precision::Fp16` guard itself.
+        let params = RuntimeParameters {
+            precision: Precision::Fp16,
+            execution_providers: vec![ort::execution_providers::CUDAExecutionProvider::default().build()],
+            ..RuntimeParameters::default()
+        };
+        let result = Model::new("nonexistent.onnx", params);
+        let message = result.err().map(|error| error.to_string()).unwrap_or_default();
+        assert!(!message.contains("requires a GPU-capable execution provider"));
+    }
 }
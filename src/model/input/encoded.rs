@@ -1,7 +1,51 @@
 use crate::util::result::Result;
 use crate::{text::{token::Token, tokenizer::Tokenizer}, util::compose::Composable};
 use super::prompt::PromptInput;
-use ndarray::{Array, Array2, ArrayView};
+use super::super::params::{Parameters, TruncationStrategy};
+use ndarray::{s, Array, Array2, ArrayView};
+
+/// Marker/special token IDs, resolved from the tokenizer's own vocabulary rather than
+/// hardcoded: different GLiNER checkpoints are built on different base encoders, each
+/// with its own BOS/EOS IDs and its own vocabulary slots for the `<<ENT>>` / `<<SEP>>`
+/// markers that separate entity labels from the input text. This mirrors the way HF
+/// tokenizer pipelines resolve markers from a `special_tokens_map.json`.
+pub struct SpecialTokens {
+    pub bos: i64,
+    pub eos: i64,
+    pub entity_marker: i64,
+    pub separator: i64,
+    pub pad: i64,
+}
+
+impl SpecialTokens {
+    const BOS_TOKEN: &'static str = "<s>";
+    const EOS_TOKEN: &'static str = "</s>";
+    const ENTITY_MARKER_TOKEN: &'static str = "<<ENT>>";
+    const SEPARATOR_TOKEN: &'static str = "<<SEP>>";
+    const PAD_TOKEN: &'static str = "<pad>";
+
+    /// Resolves every marker token's ID from the given tokenizer's vocabulary. Unlike
+    /// the other markers, `pad` is never semantically read back (every padded position
+    /// is already masked out via `attention_mask == 0`), so a tokenizer with no
+    /// explicit `<pad>` entry falls back to `0` instead of failing the whole load --
+    /// matching the portability this struct exists for, rather than regressing it for
+    /// checkpoints that pad implicitly via id `0`.
+    pub fn from_tokenizer(tokenizer: &impl Tokenizer) -> Result<Self> {
+        Ok(Self {
+            bos: Self::resolve(tokenizer, Self::BOS_TOKEN)?,
+            eos: Self::resolve(tokenizer, Self::EOS_TOKEN)?,
+            entity_marker: Self::resolve(tokenizer, Self::ENTITY_MARKER_TOKEN)?,
+            separator: Self::resolve(tokenizer, Self::SEPARATOR_TOKEN)?,
+            pad: Self::resolve(tokenizer, Self::PAD_TOKEN).unwrap_or(0),
+        })
+    }
+
+    fn resolve(tokenizer: &impl Tokenizer, token: &str) -> Result<i64> {
+        tokenizer.token_to_id(token)
+            .map(|id| id as i64)
+            .ok_or_else(|| format!("tokenizer vocabulary has no entry for special token `{token}`").into())
+    }
+}
 
 /// Represents encoded prompts (after sub-word tokenization)
 pub struct EncodedInput {
@@ -24,42 +68,109 @@ struct EncodedPrompt {
     text_offset: usize,
 }
 
+/// Encodes a single prompt's words into sub-word token sequences, applying truncation
+/// per `params`. Pulled out of `EncodedInput::from` so it can be mapped either
+/// sequentially or (with the `parallel` feature) across a rayon thread pool -- the two
+/// call sites must stay behaviorally identical.
+fn encode_prompt(prompt: &super::prompt::Prompt, tokenizer: &impl Tokenizer, params: &Parameters) -> Result<(EncodedPrompt, usize, usize)> {
+    // resulting sequence of encodings for each word of the current prompt
+    let mut prompt_tokens: Vec<Vec<u32>> = Vec::with_capacity(prompt.tokens().len());
+    // total number of sub-word tokens for the current prompt (adding 2 for initial and terminal tokens)
+    let mut total_tokens: usize = 2;
+    // number of sub-word tokens for the entities part only (before the actual text)
+    let mut total_entity_tokens = 0;
+    // encode each token of the current prompt
+    for (pos, word) in prompt.tokens().iter().enumerate() {
+        // actually encode the word
+        let encoding = tokenizer.encode(word)?;
+        // increment the number of sub-word tokens accordingly
+        total_tokens += encoding.len();
+        // increment the number of sub-word tokens in the entity part (will be used to start the word masks at the right place)
+        if pos <= prompt.entities_len() {
+            total_entity_tokens += encoding.len();
+        }
+        prompt_tokens.push(encoding);
+    }
+    // Truncate trailing text words if the prompt overflows `max_length`. The
+    // entity-label prefix (and the terminal token, accounted for in the `2`
+    // above) are never dropped: only whole words after `entities_len()` are
+    // eligible, so `prompt_tokens` never shrinks below the entity prefix.
+    let mut dropped_words = 0;
+    if let (Some(max_length), false) = (params.max_length, params.truncation_strategy == TruncationStrategy::None) {
+        while total_tokens > max_length && prompt_tokens.len() > prompt.entities_len() + 1 {
+            let dropped = prompt_tokens.pop().expect("loop guard ensures at least one text word remains");
+            total_tokens -= dropped.len();
+            dropped_words += 1;
+        }
+    }
+    Ok((EncodedPrompt { encoding: prompt_tokens, text_offset: total_entity_tokens }, dropped_words, total_tokens))
+}
+
+/// Encodes every prompt, in parallel across `pool` if given (or rayon's global pool
+/// otherwise). Callers that encode repeatedly (e.g. [`PromptsToEncoded`]) should build
+/// the pool once and pass it in every time, rather than rebuilding it per call:
+/// `rayon::ThreadPoolBuilder::build` spins up `threads` OS threads, which is wasted
+/// work to repeat per request.
+#[cfg(feature = "parallel")]
+fn encode_prompts<T: Tokenizer + Sync>(prompts: &[super::prompt::Prompt], tokenizer: &T, params: &Parameters, pool: Option<&rayon::ThreadPool>) -> Result<Vec<(EncodedPrompt, usize, usize)>> {
+    use rayon::prelude::*;
+    let encode_all = || prompts.par_iter().map(|prompt| encode_prompt(prompt, tokenizer, params)).collect();
+    match pool {
+        Some(pool) => pool.install(encode_all),
+        None => encode_all(),
+    }
+}
+
+#[cfg(not(feature = "parallel"))]
+fn encode_prompts(prompts: &[super::prompt::Prompt], tokenizer: &impl Tokenizer, params: &Parameters) -> Result<Vec<(EncodedPrompt, usize, usize)>> {
+    prompts.iter().map(|prompt| encode_prompt(prompt, tokenizer, params)).collect()
+}
+
 impl EncodedInput {
 
-    // Each word of each prompt is encoded *one by one*. So each word generates an encoding as 
-    // a Vec<u32> (sub-word tokenization). So for each prompt we get a Vec<Vec<u32>> (which is 
+    // Each word of each prompt is encoded *one by one*. So each word generates an encoding as
+    // a Vec<u32> (sub-word tokenization). So for each prompt we get a Vec<Vec<u32>> (which is
     // stored in the 'encoding' field).
-    pub fn from(input: PromptInput, tokenizer: &impl Tokenizer) -> Result<Self> {        
+    #[cfg(not(feature = "parallel"))]
+    pub fn from(input: PromptInput, tokenizer: &impl Tokenizer, special_tokens: &SpecialTokens, params: &Parameters) -> Result<Self> {
+        let per_prompt = encode_prompts(&input.prompts, tokenizer, params)?;
+        Self::assemble(input, special_tokens, per_prompt)
+    }
+
+    /// Same as the non-`parallel` [`Self::from`], but encodes prompts in parallel on
+    /// rayon's global pool (no per-call pool of its own). Prefer [`Self::from_with_pool`]
+    /// when encoding repeatedly, so the pool is built once and reused.
+    #[cfg(feature = "parallel")]
+    pub fn from<T: Tokenizer + Sync>(input: PromptInput, tokenizer: &T, special_tokens: &SpecialTokens, params: &Parameters) -> Result<Self> {
+        Self::from_with_pool(input, tokenizer, special_tokens, params, None)
+    }
+
+    /// Same as [`Self::from`], but encodes prompts in parallel across `pool` (or rayon's
+    /// global pool if `None`). Row order is still deterministic: only
+    /// the per-prompt encoding is parallelized, the tensor-assembly phase below stays
+    /// serial to avoid `push_row` races. Build `pool` once and reuse it across calls
+    /// (see [`PromptsToEncoded`]) rather than rebuilding it per call.
+    #[cfg(feature = "parallel")]
+    pub fn from_with_pool<T: Tokenizer + Sync>(input: PromptInput, tokenizer: &T, special_tokens: &SpecialTokens, params: &Parameters, pool: Option<&rayon::ThreadPool>) -> Result<Self> {
+        let per_prompt = encode_prompts(&input.prompts, tokenizer, params, pool)?;
+        Self::assemble(input, special_tokens, per_prompt)
+    }
+
+    fn assemble(input: PromptInput, special_tokens: &SpecialTokens, per_prompt: Vec<(EncodedPrompt, usize, usize)>) -> Result<Self> {
+
+        // number of words truncated off the text of each prompt (parallels `input.text_lengths`)
+        let mut truncated_words: Vec<usize> = Vec::with_capacity(per_prompt.len());
         // prepare the result vector
-        let mut encodings: Vec<EncodedPrompt> = Vec::with_capacity(input.prompts.len());
+        let mut encodings: Vec<EncodedPrompt> = Vec::with_capacity(per_prompt.len());
         // maximum number of sub-word tokens found in one prompt (will be the width of the input tensor)
         let mut max_tokens: usize = 0;
-        // process each prompt
-        for prompt in &input.prompts {
-            // resulting sequence of encodings for each word of the current prompt
-            let mut prompt_tokens: Vec<Vec<u32>> = Vec::with_capacity(prompt.tokens().len());
-            // total number of sub-word tokens for the current prompt (adding 2 for initial and terminal tokens)
-            let mut total_tokens: usize = 2;
-            // number of sub-word tokens for the entities part only (before the actual text)
-            let mut total_entity_tokens = 0;
-            // encode each token of the current prompt
-            for (pos, word) in prompt.tokens().iter().enumerate() {
-                // actually encode the word
-                let encoding = tokenizer.encode(word)?;
-                // increment the number of sub-word tokens accordingly
-                total_tokens += encoding.len();
-                // increment the number of sub-word tokens in the entity part (will be used to start the word masks at the right place)
-                if pos <= prompt.entities_len() {
-                    total_entity_tokens += encoding.len();
-                }
-                prompt_tokens.push(encoding);
-            }
-            // update global result: push encoded prompt and update max_tokens
-            encodings.push(EncodedPrompt { encoding: prompt_tokens, text_offset: total_entity_tokens });
+        for (encoded_prompt, dropped_words, total_tokens) in per_prompt {
+            truncated_words.push(dropped_words);
             max_tokens = std::cmp::max(max_tokens, total_tokens);
+            encodings.push(encoded_prompt);
         }
 
-        // Compute vectors for each prompt. The `encoding` structure (which is 
+        // Compute vectors for each prompt. The `encoding` structure (which is
         // word by word) gets flattened, but the word-level information is 
         // still represented by the "word mask".
         let mut input_ids = Array::zeros((0, max_tokens));
@@ -67,16 +178,16 @@ impl EncodedInput {
         let mut word_masks = Array::zeros((0, max_tokens));
         for encoded_prompt in encodings {
             let encoding = encoded_prompt.encoding;
-            let mut input_id = vec!(0i64; max_tokens);
+            let mut input_id = vec!(special_tokens.pad; max_tokens);
             let mut attn_mask = vec!(0i64; max_tokens);
-            let mut word_mask = vec!(0i64; max_tokens);            
+            let mut word_mask = vec!(0i64; max_tokens);
 
             let mut idx: usize = 0;
             let mut word_id: i64 = 0;
 
             // add initial token
-            input_id[idx] = 1;
-            attn_mask[idx] = 1;            
+            input_id[idx] = special_tokens.bos;
+            attn_mask[idx] = 1;
             idx += 1;
 
             // process each encoded (sub-word) token
@@ -99,7 +210,7 @@ impl EncodedInput {
             }
 
             // add terminal token
-            input_id[idx] = 2;
+            input_id[idx] = special_tokens.eos;
             attn_mask[idx] = 1;
 
             // update final results
@@ -108,9 +219,11 @@ impl EncodedInput {
             word_masks.push_row(ArrayView::from(&word_mask))?;
         }
 
-        // text lengths (this data is fundamentally one-dimensional, but the model expects a two-dimensional one)
+        // text lengths (this data is fundamentally one-dimensional, but the model expects a two-dimensional one).
+        // Clamped by however many trailing words truncation dropped, so downstream span offsets stay in bounds.
         let mut text_lengths = Array::zeros((0, 1));
-        for text_length in input.text_lengths {
+        for (text_length, dropped_words) in input.text_lengths.into_iter().zip(truncated_words) {
+            let text_length = text_length.saturating_sub(dropped_words);
             text_lengths.push_row(ArrayView::from(&vec![text_length as i64]))?;
         }
 
@@ -124,28 +237,151 @@ impl EncodedInput {
             input_ids,
             attention_masks,
             word_masks,
-            text_lengths,            
+            text_lengths,
         })
     }
 
+    /// Splits the rows of this (already batch-wide-padded) `EncodedInput` into
+    /// length-bucketed mini-batches: rows are sorted by their true length (the sum of
+    /// their attention mask) and grouped into buckets of up to `bucket_rows` rows,
+    /// each re-padded only to its own (smaller) max length, instead of every row
+    /// paying for the single longest row in the whole batch. `max_batch_tokens`, if
+    /// set, additionally caps a bucket's `rows * bucket_max_len`; a bucket never drops
+    /// below one row, so a single row longer than the cap still forms its own bucket.
+    ///
+    /// Returns each bucket alongside the original row indices it contains, so the
+    /// caller can run the model per bucket and stitch the outputs back into the
+    /// input's original order.
+    pub fn into_buckets(self, bucket_rows: usize, max_batch_tokens: Option<usize>) -> Result<Vec<(Vec<usize>, EncodedInput)>> {
+        let num_rows = self.input_ids.nrows();
+        // `bucket_rows == 0` has no sensible bucket size, so treat it like "no
+        // bucketing" and return the whole batch as a single bucket. `bucket_rows == 1`
+        // is NOT special-cased here: it means "one row per bucket", and must still go
+        // through the splitting logic below so each row is re-padded to its own length.
+        if num_rows == 0 || bucket_rows == 0 {
+            return Ok(vec![((0..num_rows).collect(), self)]);
+        }
+
+        let lengths: Vec<usize> = self.attention_masks.outer_iter()
+            .map(|row| row.iter().sum::<i64>() as usize)
+            .collect();
+        let mut order: Vec<usize> = (0..num_rows).collect();
+        order.sort_by_key(|&i| lengths[i]);
+
+        let mut buckets = Vec::new();
+        let mut start = 0;
+        while start < order.len() {
+            let mut end = std::cmp::min(start + bucket_rows, order.len());
+            if let Some(max_batch_tokens) = max_batch_tokens {
+                while end > start + 1 {
+                    let bucket_max_len = order[start..end].iter().map(|&i| lengths[i]).max().unwrap_or(0);
+                    if (end - start) * bucket_max_len <= max_batch_tokens {
+                        break;
+                    }
+                    end -= 1;
+                }
+            }
+
+            let indices: Vec<usize> = order[start..end].to_vec();
+            let bucket_max_len = indices.iter().map(|&i| lengths[i]).max().unwrap_or(0).max(1);
+
+            let mut input_ids = Array::zeros((0, bucket_max_len));
+            let mut attention_masks = Array::zeros((0, bucket_max_len));
+            let mut word_masks = Array::zeros((0, bucket_max_len));
+            let mut text_lengths = Array::zeros((0, 1));
+            for &idx in &indices {
+                // the original rows are already padded to `self.num_tokens >= bucket_max_len`,
+                // and padding lives past each row's real length, so truncating to the first
+                // `bucket_max_len` columns keeps every real token and only drops excess padding.
+                input_ids.push_row(self.input_ids.row(idx).slice(s![..bucket_max_len]))?;
+                attention_masks.push_row(self.attention_masks.row(idx).slice(s![..bucket_max_len]))?;
+                word_masks.push_row(self.word_masks.row(idx).slice(s![..bucket_max_len]))?;
+                text_lengths.push_row(self.text_lengths.row(idx))?;
+            }
+
+            // `texts`/`tokens` are per-row: slice them down to this bucket's rows rather
+            // than cloning the whole batch into every bucket. `num_words` is the max
+            // word count across this bucket's rows only, mirroring how `num_tokens`
+            // above is `bucket_max_len` rather than the whole batch's `self.num_tokens`.
+            let texts: Vec<String> = indices.iter().map(|&idx| self.texts[idx].clone()).collect();
+            let tokens: Vec<Vec<Token>> = indices.iter().map(|&idx| self.tokens[idx].clone()).collect();
+            let num_words = tokens.iter().map(|words| words.len()).max().unwrap_or(0);
+
+            buckets.push((indices, EncodedInput {
+                texts,
+                tokens,
+                entities: self.entities.clone(),
+                num_words,
+                num_tokens: bucket_max_len,
+                input_ids,
+                attention_masks,
+                word_masks,
+                text_lengths,
+            }));
+            start = end;
+        }
+
+        Ok(buckets)
+    }
+
 }
 
 
 
 /// Composable: Prompts => Encoded
-pub struct PromptsToEncoded<'a, T> { 
+#[cfg(not(feature = "parallel"))]
+pub struct PromptsToEncoded<'a, T> {
     tokenizer: &'a T,
+    special_tokens: SpecialTokens,
+    params: Parameters,
 }
 
-impl<'a, T> PromptsToEncoded<'a, T> {
-    pub fn new(tokenizer: &'a T) -> Self {
-        Self { tokenizer }
+#[cfg(not(feature = "parallel"))]
+impl<'a, T: Tokenizer> PromptsToEncoded<'a, T> {
+    /// Resolves the special tokens from `tokenizer`'s vocabulary once, at construction time.
+    pub fn new(tokenizer: &'a T, params: Parameters) -> Result<Self> {
+        let special_tokens = SpecialTokens::from_tokenizer(tokenizer)?;
+        Ok(Self { tokenizer, special_tokens, params })
     }
 }
 
+#[cfg(not(feature = "parallel"))]
 impl<'a, T: Tokenizer> Composable<PromptInput, EncodedInput> for PromptsToEncoded<'a, T> {
     fn apply(&self, input: PromptInput) -> Result<EncodedInput> {
-        EncodedInput::from(input, self.tokenizer)
+        EncodedInput::from(input, self.tokenizer, &self.special_tokens, &self.params)
+    }
+}
+
+/// Composable: Prompts => Encoded. Encodes prompts in parallel over a rayon thread
+/// pool built once, at construction (see `RuntimeParameters::encoding_threads`),
+/// and reused for every `apply()` call -- rebuilding a `rayon::ThreadPool` (which
+/// spins up OS threads) on every request would waste exactly the latency this
+/// feature exists to cut.
+#[cfg(feature = "parallel")]
+pub struct PromptsToEncoded<'a, T> {
+    tokenizer: &'a T,
+    special_tokens: SpecialTokens,
+    params: Parameters,
+    thread_pool: Option<rayon::ThreadPool>,
+}
+
+#[cfg(feature = "parallel")]
+impl<'a, T: Tokenizer> PromptsToEncoded<'a, T> {
+    /// Resolves the special tokens from `tokenizer`'s vocabulary and builds the rayon
+    /// thread pool (if `encoding_threads` is set), once, at construction time.
+    pub fn new(tokenizer: &'a T, params: Parameters, encoding_threads: Option<usize>) -> Result<Self> {
+        let special_tokens = SpecialTokens::from_tokenizer(tokenizer)?;
+        let thread_pool = encoding_threads
+            .map(|threads| rayon::ThreadPoolBuilder::new().num_threads(threads).build())
+            .transpose()?;
+        Ok(Self { tokenizer, special_tokens, params, thread_pool })
+    }
+}
+
+#[cfg(feature = "parallel")]
+impl<'a, T: Tokenizer + Sync> Composable<PromptInput, EncodedInput> for PromptsToEncoded<'a, T> {
+    fn apply(&self, input: PromptInput) -> Result<EncodedInput> {
+        EncodedInput::from_with_pool(input, self.tokenizer, &self.special_tokens, &self.params, self.thread_pool.as_ref())
     }
 }
 
@@ -164,7 +400,9 @@ mod tests {
         let input = super::super::text::TextInput::from_str(&batch, &entities)?;
         let tokenized = super::super::tokenized::TokenizedInput::from(input, &splitter, None)?;
         let prepared = PromptInput::from(tokenized);
-        let encoded = EncodedInput::from(prepared, &tokenizer)?;
+        let special_tokens = SpecialTokens::from_tokenizer(&tokenizer)?;
+        let params = Parameters::default();
+        let encoded = EncodedInput::from(prepared, &tokenizer, &special_tokens, &params)?;
         // Some prints
         if false {
             println!("### {:?}", encoded.num_tokens);
@@ -173,19 +411,17 @@ mod tests {
             println!("Word masks: {:?}", encoded.word_masks);
         }
         // Assertions on input ids
-        const ENT_ID: i64 = 128002;
-        const SEP_ID: i64 = 128003;
         assert_eq!(encoded.num_tokens, 22);
         let ids1 = encoded.input_ids.row(0);
         let ids2 = encoded.input_ids.row(1);
         assert_eq!(ids1.len(), encoded.num_tokens);
         assert_eq!(ids2.len(), encoded.num_tokens);
-        assert_eq!(ids1.iter().filter(|id| **id == 0).count(), 13);
-        assert_eq!(ids1.iter().filter(|id| **id == ENT_ID).count(), 2);
-        assert_eq!(ids1.iter().filter(|id| **id == SEP_ID).count(), 1);
-        assert_eq!(ids2.iter().filter(|id| **id == 0).count(), 0);
-        assert_eq!(ids2.iter().filter(|id| **id == ENT_ID).count(), 2);
-        assert_eq!(ids2.iter().filter(|id| **id == SEP_ID).count(), 1);
+        assert_eq!(ids1.iter().filter(|id| **id == special_tokens.pad).count(), 13);
+        assert_eq!(ids1.iter().filter(|id| **id == special_tokens.entity_marker).count(), 2);
+        assert_eq!(ids1.iter().filter(|id| **id == special_tokens.separator).count(), 1);
+        assert_eq!(ids2.iter().filter(|id| **id == special_tokens.pad).count(), 0);
+        assert_eq!(ids2.iter().filter(|id| **id == special_tokens.entity_marker).count(), 2);
+        assert_eq!(ids2.iter().filter(|id| **id == special_tokens.separator).count(), 1);
         // Assertions on attention mask
         let attn1 = encoded.attention_masks.row(0);
         let attn2 = encoded.attention_masks.row(1);
@@ -204,7 +440,9 @@ mod tests {
         let input = super::super::text::TextInput::from_str(&batch, &entities)?;
         let tokenized = super::super::tokenized::TokenizedInput::from(input, &splitter, None)?;
         let prepared = PromptInput::from(tokenized);
-        let encoded = EncodedInput::from(prepared, &tokenizer)?;
+        let special_tokens = SpecialTokens::from_tokenizer(&tokenizer)?;
+        let params = Parameters::default();
+        let encoded = EncodedInput::from(prepared, &tokenizer, &special_tokens, &params)?;
         // Some prints
         if false {
             println!("### {:?}", encoded.num_tokens);
@@ -213,12 +451,13 @@ mod tests {
             println!("Word masks: {:?}", encoded.word_masks);
             println!("Text length: {:?}", encoded.text_lengths);
         }
+        let (bos, eos, ent, sep, pad) = (special_tokens.bos, special_tokens.eos, special_tokens.entity_marker, special_tokens.separator, special_tokens.pad);
         // Assertions on first sequence
         let ids1 = encoded.input_ids.row(0);
         let attn1 = encoded.attention_masks.row(0);
         let word1 = encoded.word_masks.row(0);
-        let len1 = encoded.text_lengths.row(0);        
-        assert_eq!(ids1.to_vec(), vec![1, 128002, 1421, 1470, 128002, 1508, 128003, 573, 601, 269, 1749, 8728, 2, 0, 0]);
+        let len1 = encoded.text_lengths.row(0);
+        assert_eq!(ids1.to_vec(), vec![bos, ent, 1421, 1470, ent, 1508, sep, 573, 601, 269, 1749, 8728, eos, pad, pad]);
         assert_eq!(attn1.to_vec(), vec![1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0, 0]);
         assert_eq!(word1.to_vec(), vec![0, 0, 0, 0, 0, 0, 0, 1, 2, 3, 4, 5, 0, 0, 0]);
         assert_eq!(len1.to_vec(), vec![5]);
@@ -227,7 +466,7 @@ mod tests {
         let attn2 = encoded.attention_masks.row(1);
         let word2 = encoded.word_masks.row(1);
         let len2 = encoded.text_lengths.row(1);
-        assert_eq!(ids2.to_vec(), vec![1, 128002, 1421, 1470, 128002, 1508, 128003, 273, 334, 264, 1168, 312, 20844, 2963, 2]);
+        assert_eq!(ids2.to_vec(), vec![bos, ent, 1421, 1470, ent, 1508, sep, 273, 334, 264, 1168, 312, 20844, 2963, eos]);
         assert_eq!(attn2.to_vec(), vec![1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1]);
         assert_eq!(word2.to_vec(), vec![0, 0, 0, 0, 0, 0, 0, 1, 2, 3, 4, 5, 6, 7, 0]);
         assert_eq!(len2.to_vec(), vec![7]);
@@ -243,7 +482,9 @@ mod tests {
         let input = super::super::text::TextInput::from_str(&batch, &entities)?;
         let tokenized = super::super::tokenized::TokenizedInput::from(input, &splitter, None)?;
         let prepared = PromptInput::from(tokenized);
-        let encoded = EncodedInput::from(prepared, &tokenizer)?;
+        let special_tokens = SpecialTokens::from_tokenizer(&tokenizer)?;
+        let params = Parameters::default();
+        let encoded = EncodedInput::from(prepared, &tokenizer, &special_tokens, &params)?;
         // Some prints
         if false {
             println!("### {:?}", encoded.num_tokens);
@@ -260,4 +501,111 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_truncation() -> Result<()> {
+        let splitter = crate::text::splitter::RegexSplitter::default();
+        let tokenizer = crate::text::tokenizer::HFTokenizer::from_file("models/gliner_small-v2.1/tokenizer.json")?;
+        let batch = [ "I like to drive my Aston Martin" ];
+        let entities = [ "movie character", "vehicle" ];
+        let input = super::super::text::TextInput::from_str(&batch, &entities)?;
+        let tokenized = super::super::tokenized::TokenizedInput::from(input, &splitter, None)?;
+        let prepared = PromptInput::from(tokenized);
+        let special_tokens = SpecialTokens::from_tokenizer(&tokenizer)?;
+        // Without truncation, this prompt encodes to 15 tokens (see `test2`). Ask for less.
+        let params = Parameters { max_length: Some(10), truncation_strategy: TruncationStrategy::LongestFirst, ..Parameters::default() };
+        let encoded = EncodedInput::from(prepared, &tokenizer, &special_tokens, &params)?;
+        // The entity-label prefix (7 tokens: bos, ent, "movie", "character", ent, "vehicle", sep)
+        // is always kept, so nothing shorter than that can come out.
+        assert!(encoded.num_tokens <= 10);
+        assert!(encoded.num_tokens >= 7);
+        assert!(encoded.text_lengths.row(0)[0] < 7);
+        Ok(())
+    }
+
+    #[test]
+    fn test_no_truncation_by_default() -> Result<()> {
+        let splitter = crate::text::splitter::RegexSplitter::default();
+        let tokenizer = crate::text::tokenizer::HFTokenizer::from_file("models/gliner_small-v2.1/tokenizer.json")?;
+        let batch = [ "I like to drive my Aston Martin" ];
+        let entities = [ "movie character", "vehicle" ];
+        let input = super::super::text::TextInput::from_str(&batch, &entities)?;
+        let tokenized = super::super::tokenized::TokenizedInput::from(input, &splitter, None)?;
+        let prepared = PromptInput::from(tokenized);
+        let special_tokens = SpecialTokens::from_tokenizer(&tokenizer)?;
+        let params = Parameters::default();
+        let encoded = EncodedInput::from(prepared, &tokenizer, &special_tokens, &params)?;
+        assert_eq!(encoded.num_tokens, 15);
+        assert_eq!(encoded.text_lengths.row(0)[0], 7);
+        Ok(())
+    }
+
+    #[test]
+    fn test_into_buckets_pads_each_bucket_to_its_own_length() -> Result<()> {
+        let splitter = crate::text::splitter::RegexSplitter::default();
+        let tokenizer = crate::text::tokenizer::HFTokenizer::from_file("models/gliner_small-v2.1/tokenizer.json")?;
+        let batch = [ "My name is James Bond", "I like to drive my Aston Martin"];
+        let entities = [ "movie character", "vehicle" ];
+        let input = super::super::text::TextInput::from_str(&batch, &entities)?;
+        let tokenized = super::super::tokenized::TokenizedInput::from(input, &splitter, None)?;
+        let prepared = PromptInput::from(tokenized);
+        let special_tokens = SpecialTokens::from_tokenizer(&tokenizer)?;
+        let params = Parameters::default();
+        // row 0 is 13 real tokens, row 1 is 15: batch-wide padding inflates row 0 to 15.
+        let encoded = EncodedInput::from(prepared, &tokenizer, &special_tokens, &params)?;
+        assert_eq!(encoded.num_tokens, 15);
+
+        let buckets = encoded.into_buckets(1, None)?;
+        assert_eq!(buckets.len(), 2);
+        // sorted by ascending true length, so the 13-token row comes first
+        let (short_indices, short_bucket) = &buckets[0];
+        let (long_indices, long_bucket) = &buckets[1];
+        assert_eq!(short_indices, &vec![0]);
+        assert_eq!(short_bucket.num_tokens, 13);
+        assert_eq!(long_indices, &vec![1]);
+        assert_eq!(long_bucket.num_tokens, 15);
+        Ok(())
+    }
+
+    #[test]
+    fn test_into_buckets_slices_texts_and_tokens_per_bucket() -> Result<()> {
+        let splitter = crate::text::splitter::RegexSplitter::default();
+        let tokenizer = crate::text::tokenizer::HFTokenizer::from_file("models/gliner_small-v2.1/tokenizer.json")?;
+        let batch = [ "My name is James Bond", "I like to drive my Aston Martin"];
+        let entities = [ "movie character", "vehicle" ];
+        let input = super::super::text::TextInput::from_str(&batch, &entities)?;
+        let tokenized = super::super::tokenized::TokenizedInput::from(input, &splitter, None)?;
+        let prepared = PromptInput::from(tokenized);
+        let special_tokens = SpecialTokens::from_tokenizer(&tokenizer)?;
+        let params = Parameters::default();
+        let encoded = EncodedInput::from(prepared, &tokenizer, &special_tokens, &params)?;
+
+        let buckets = encoded.into_buckets(1, None)?;
+        let (short_indices, short_bucket) = &buckets[0];
+        let (long_indices, long_bucket) = &buckets[1];
+        assert_eq!(short_bucket.texts, vec![batch[short_indices[0]].to_string()]);
+        assert_eq!(short_bucket.tokens.len(), 1);
+        assert_eq!(long_bucket.texts, vec![batch[long_indices[0]].to_string()]);
+        assert_eq!(long_bucket.tokens.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_into_buckets_zero_bucket_rows_disables_bucketing() -> Result<()> {
+        let splitter = crate::text::splitter::RegexSplitter::default();
+        let tokenizer = crate::text::tokenizer::HFTokenizer::from_file("models/gliner_small-v2.1/tokenizer.json")?;
+        let batch = [ "My name is James Bond", "I like to drive my Aston Martin"];
+        let entities = [ "movie character", "vehicle" ];
+        let input = super::super::text::TextInput::from_str(&batch, &entities)?;
+        let tokenized = super::super::tokenized::TokenizedInput::from(input, &splitter, None)?;
+        let prepared = PromptInput::from(tokenized);
+        let special_tokens = SpecialTokens::from_tokenizer(&tokenizer)?;
+        let params = Parameters::default();
+        let encoded = EncodedInput::from(prepared, &tokenizer, &special_tokens, &params)?;
+
+        let buckets = encoded.into_buckets(0, None)?;
+        assert_eq!(buckets.len(), 1);
+        assert_eq!(buckets[0].0, vec![0, 1]);
+        Ok(())
+    }
+
 }
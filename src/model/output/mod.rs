@@ -0,0 +1,153 @@
+//! Post-processing: turning raw (start, end, label, score) candidates into the
+//! non-contradictory entity spans callers actually want.
+
+use ndarray::ArrayView3;
+use super::params::{ConsolidationStrategy, Parameters};
+
+/// A candidate (or, after [`consolidate`], accepted) entity span. Offsets are word
+/// indices into the original text, matching the `word_mask` produced during encoding.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub label: String,
+    pub score: f32,
+}
+
+impl ConsolidationStrategy {
+    /// Whether `candidate` conflicts with an already-`accepted` span under this strategy.
+    fn conflicts(&self, accepted: &Span, candidate: &Span) -> bool {
+        // `start`/`end` are inclusive word indices (see `decode_spans`'s `text[start..=end]`),
+        // so spans sharing only their boundary word (e.g. (0,3) and (3,5)) already overlap on
+        // that word -- hence `<=` rather than the half-open-interval `<`.
+        let overlaps = candidate.start <= accepted.end && accepted.start <= candidate.end;
+        if !overlaps {
+            return false;
+        }
+        match self {
+            // Any overlap at all is a conflict.
+            ConsolidationStrategy::Flat => true,
+            // Fully-contained nestings are fine; only a partial cross is a conflict.
+            ConsolidationStrategy::Nested => {
+                let candidate_nests_in_accepted = accepted.start <= candidate.start && candidate.end <= accepted.end;
+                let accepted_nests_in_candidate = candidate.start <= accepted.start && accepted.end <= candidate.end;
+                !(candidate_nests_in_accepted || accepted_nests_in_candidate)
+            }
+        }
+    }
+}
+
+/// Consolidates candidate spans emitted by the model into a clean, non-contradictory
+/// set: drops anything below `threshold`, then greedily accepts spans by descending
+/// score, skipping any that conflict (per `strategy`) with an already-accepted span.
+/// The result is sorted by start offset, as callers expect.
+pub fn consolidate(candidates: Vec<Span>, strategy: ConsolidationStrategy, threshold: f32) -> Vec<Span> {
+    let mut candidates: Vec<Span> = candidates.into_iter().filter(|span| span.score >= threshold).collect();
+    candidates.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+
+    let mut accepted: Vec<Span> = Vec::with_capacity(candidates.len());
+    for candidate in candidates {
+        if !accepted.iter().any(|span| strategy.conflicts(span, &candidate)) {
+            accepted.push(candidate);
+        }
+    }
+
+    accepted.sort_by_key(|span| span.start);
+    accepted
+}
+
+/// [`consolidate`], reading its strategy and threshold from `params` instead of
+/// taking them as separate arguments -- this is what actually ties
+/// `Parameters::consolidation_strategy`/`Parameters::threshold` to real inference (see
+/// [`crate::model::inference::Model::infer_spans`]), rather than leaving them as
+/// configuration only a hand-written caller could read.
+pub fn consolidate_with_params(candidates: Vec<Span>, params: &Parameters) -> Vec<Span> {
+    consolidate(candidates, params.consolidation_strategy, params.threshold)
+}
+
+/// Decodes one row's raw span-score tensor into scored candidate spans, ready for
+/// [`consolidate`]. `tensor[[start, end, entity]]` is the model's raw logit for the
+/// span `text[start..=end]` (word indices, matching `word_mask`) being labeled
+/// `entities[entity]`; a sigmoid turns it into a `[0, 1]` score. Only `start <= end`
+/// pairs within the row's real (unpadded) `num_words` are considered.
+pub fn decode_spans(tensor: ArrayView3<f32>, num_words: usize, entities: &[String]) -> Vec<Span> {
+    let mut candidates = Vec::new();
+    for start in 0..num_words {
+        for end in start..num_words {
+            for (entity, label) in entities.iter().enumerate() {
+                let score = sigmoid(tensor[[start, end, entity]]);
+                candidates.push(Span { start, end, label: label.clone(), score });
+            }
+        }
+    }
+    candidates
+}
+
+fn sigmoid(logit: f32) -> f32 {
+    1.0 / (1.0 + (-logit).exp())
+}
+
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(start: usize, end: usize, score: f32) -> Span {
+        Span { start, end, label: "Person".to_string(), score }
+    }
+
+    #[test]
+    fn test_flat_drops_any_overlap() {
+        let candidates = vec![span(0, 3, 0.9), span(2, 5, 0.8), span(6, 8, 0.7)];
+        let result = consolidate(candidates, ConsolidationStrategy::Flat, 0.0);
+        assert_eq!(result, vec![span(0, 3, 0.9), span(6, 8, 0.7)]);
+    }
+
+    #[test]
+    fn test_flat_drops_spans_sharing_only_a_boundary_word() {
+        // (0,3) and (3,5) share word index 3, which is a conflict under inclusive
+        // (closed-interval) span semantics even though the ranges don't overlap if
+        // treated as half-open.
+        let candidates = vec![span(0, 3, 0.9), span(3, 5, 0.8)];
+        let result = consolidate(candidates, ConsolidationStrategy::Flat, 0.0);
+        assert_eq!(result, vec![span(0, 3, 0.9)]);
+    }
+
+    #[test]
+    fn test_nested_keeps_fully_contained_spans() {
+        let candidates = vec![span(0, 10, 0.9), span(2, 4, 0.8), span(3, 12, 0.7)];
+        let result = consolidate(candidates, ConsolidationStrategy::Nested, 0.0);
+        // (3, 12) partially crosses the accepted (0, 10) so it's dropped, but the
+        // fully-nested (2, 4) is kept.
+        assert_eq!(result, vec![span(0, 10, 0.9), span(2, 4, 0.8)]);
+    }
+
+    #[test]
+    fn test_threshold_drops_low_score_spans() {
+        let candidates = vec![span(0, 3, 0.9), span(4, 6, 0.2)];
+        let result = consolidate(candidates, ConsolidationStrategy::Flat, 0.5);
+        assert_eq!(result, vec![span(0, 3, 0.9)]);
+    }
+
+    #[test]
+    fn test_consolidate_with_params_reads_strategy_and_threshold_from_parameters() {
+        let candidates = vec![span(0, 3, 0.9), span(2, 5, 0.8), span(4, 6, 0.2)];
+        let params = Parameters { consolidation_strategy: ConsolidationStrategy::Flat, threshold: 0.5, ..Parameters::default() };
+        let result = consolidate_with_params(candidates, &params);
+        assert_eq!(result, vec![span(0, 3, 0.9)]);
+    }
+
+    #[test]
+    fn test_decode_spans_only_considers_start_le_end_within_num_words() {
+        use ndarray::Array3;
+        // 2 words, 1 entity: only (0,0), (0,1), (1,1) are valid (start <= end).
+        let tensor = Array3::from_shape_fn((2, 2, 1), |(start, end, _)| if (start, end) == (0, 1) { 10.0 } else { -10.0 });
+        let entities = vec!["Person".to_string()];
+        let spans = decode_spans(tensor.view(), 2, &entities);
+        assert_eq!(spans.len(), 3);
+        let best = spans.iter().max_by(|a, b| a.score.partial_cmp(&b.score).unwrap()).unwrap();
+        assert_eq!((best.start, best.end), (0, 1));
+        assert!(best.score > 0.9);
+    }
+}
@@ -0,0 +1,148 @@
+//! Parameters controlling pre-/post-processing and the underlying ONNX runtime.
+
+use std::path::{Path, PathBuf};
+use ort::execution_providers::ExecutionProviderDispatch;
+
+/// How to handle a prompt whose encoded length exceeds [`Parameters::max_length`].
+///
+/// Named after the equivalent option in the rust-bert tokenization pipeline
+/// (`encode_list(&input, max_len, &TruncationStrategy::LongestFirst, 0)`), though here
+/// there is only ever one sequence to trim: the entity-label prefix is never touched,
+/// so `LongestFirst` and `OnlyText` both truncate words from the text portion.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum TruncationStrategy {
+    /// Drop trailing words from the text until the prompt fits `max_length`.
+    #[default]
+    LongestFirst,
+    /// Same as `LongestFirst`: only the text is ever eligible for truncation.
+    OnlyText,
+    /// Do not truncate; prompts longer than `max_length` are left as-is.
+    None,
+}
+
+/// How overlapping candidate spans are resolved during post-processing consolidation
+/// (see [`crate::model::output::consolidate`]). Named after the NER entity-consolidation
+/// modes from rust-bert.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ConsolidationStrategy {
+    /// A span conflicts with an accepted one if their token ranges overlap at all.
+    #[default]
+    Flat,
+    /// A span conflicts with an accepted one only if it partially crosses its
+    /// boundary; spans fully nested within an accepted one are kept.
+    Nested,
+}
+
+/// Parameters controlling pre-/post-processing behavior. Independent of the ONNX
+/// runtime itself (see [`RuntimeParameters`] for that).
+#[derive(Clone, Debug, Default)]
+pub struct Parameters {
+    /// Maximum number of sub-word tokens (including BOS/EOS) allowed in an encoded
+    /// prompt. `None` means no limit is enforced. The limit is enforced by dropping
+    /// trailing text words, and not at all under [`TruncationStrategy::None`]; the
+    /// entity-label prefix is never dropped, so a prompt whose prefix alone is too
+    /// long still exceeds it.
+    pub max_length: Option<usize>,
+    /// Strategy applied to prompts that exceed `max_length`.
+    pub truncation_strategy: TruncationStrategy,
+    /// How to resolve overlapping/nesting candidate spans into a clean entity set.
+    pub consolidation_strategy: ConsolidationStrategy,
+    /// Candidate spans scoring below this are dropped before consolidation.
+    pub threshold: f32,
+}
+
+/// Numeric precision the ONNX session should run the model at.
+///
+/// Borrowed from rust-bert's `half()` support: fp16/int8 trade accuracy for the
+/// memory and throughput wins lower precision gives on supported hardware. fp16
+/// kernels in `ort` are typically GPU-only (e.g. via the CUDA execution provider) --
+/// requesting `Fp16` without a GPU execution provider configured is an error (see
+/// [`crate::model::inference::Model::new`]) rather than a silent, slow CPU fallback.
+/// Regardless of precision, `input_ids`/`attention_masks`/`word_masks` stay the i64
+/// integer types the graph expects -- only the model weights shrink, plus the output
+/// logits under `Fp16` (an `Int8` model's logits are read back as f32).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Precision {
+    #[default]
+    Fp32,
+    Fp16,
+    Int8,
+}
+
+impl Precision {
+    /// The model file `Model::new` should actually load for this precision. `Fp32`
+    /// loads `model_path` unmodified; `Fp16`/`Int8` load the conventional quantized
+    /// sibling file (e.g. `model.onnx` -> `model.int8.onnx`), matching the layout
+    /// produced by `onnxruntime`'s own quantization tooling. Callers are responsible
+    /// for actually producing that sibling file -- this only picks the path.
+    pub fn resolve_model_path(&self, model_path: &Path) -> PathBuf {
+        let suffix = match self {
+            Precision::Fp32 => return model_path.to_path_buf(),
+            Precision::Fp16 => "fp16",
+            Precision::Int8 => "int8",
+        };
+
+        let stem = model_path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("model");
+        let extension = model_path.extension().and_then(|ext| ext.to_str()).unwrap_or("onnx");
+        model_path.with_file_name(format!("{stem}.{suffix}.{extension}"))
+    }
+}
+
+/// Parameters controlling the underlying ONNX runtime session.
+#[derive(Clone)]
+pub struct RuntimeParameters {
+    pub execution_providers: Vec<ExecutionProviderDispatch>,
+    pub threads: usize,
+    /// Number of rows per length-bucketed micro-batch (see
+    /// [`crate::model::input::encoded::EncodedInput::into_buckets`]). `0` disables
+    /// bucketing (the whole batch runs as one bucket); `1` runs every row as its own
+    /// bucket, padded to its own length.
+    pub bucket_rows: usize,
+    /// Caps a bucket's `rows * bucket_max_len` so a handful of long outliers in one
+    /// bucket can't blow up memory. A bucket always keeps at least one row, so a
+    /// single row longer than the cap still runs as its own bucket. `None` means only
+    /// `bucket_rows` bounds a bucket.
+    pub max_batch_tokens: Option<usize>,
+    /// Rayon thread count used to encode prompts in parallel (requires the crate's
+    /// `parallel` feature; ignored otherwise). `None` uses rayon's global pool.
+    pub encoding_threads: Option<usize>,
+    /// Numeric precision to commit the session at. See [`Precision`].
+    pub precision: Precision,
+}
+
+impl Default for RuntimeParameters {
+    fn default() -> Self {
+        Self {
+            execution_providers: Vec::new(),
+            threads: 4,
+            bucket_rows: 16,
+            max_batch_tokens: None,
+            encoding_threads: None,
+            precision: Precision::Fp32,
+        }
+    }
+}
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fp32_resolves_model_path_unmodified() {
+        let path = Path::new("models/gliner_small-v2.1/model.onnx");
+        assert_eq!(Precision::Fp32.resolve_model_path(path), path);
+    }
+
+    #[test]
+    fn test_fp16_resolves_quantized_sibling_file() {
+        let path = Path::new("models/gliner_small-v2.1/model.onnx");
+        assert_eq!(Precision::Fp16.resolve_model_path(path), Path::new("models/gliner_small-v2.1/model.fp16.onnx"));
+    }
+
+    #[test]
+    fn test_int8_resolves_quantized_sibling_file() {
+        let path = Path::new("models/gliner_small-v2.1/model.onnx");
+        assert_eq!(Precision::Int8.resolve_model_path(path), Path::new("models/gliner_small-v2.1/model.int8.onnx"));
+    }
+}